@@ -0,0 +1,298 @@
+use pubgrub::Range;
+
+use uv_distribution_types::CompatibleDist;
+use uv_normalize::PackageName;
+use uv_pep440::{Version, VersionSpecifiers};
+use uv_warnings::warn_user_once;
+
+use crate::resolver::batch_prefetch::{
+    BaseVersionOrdering, PythonCompatibilityStrategy, VersionOrdering, VersionPreference,
+};
+use crate::{PythonRequirement, ResolverEnvironment, VersionMap};
+
+/// A package version together with the distribution we'd install for it, if any satisfies the
+/// current platform.
+#[derive(Debug, Clone)]
+pub(crate) struct Candidate {
+    name: PackageName,
+    version: Version,
+    dist: Option<CompatibleDist>,
+}
+
+impl Candidate {
+    fn new(name: PackageName, version: Version, dist: Option<CompatibleDist>) -> Self {
+        Self {
+            name,
+            version,
+            dist,
+        }
+    }
+
+    pub(crate) fn version(&self) -> &Version {
+        &self.version
+    }
+
+    /// The distribution we'd install for this version, if the platform has one.
+    pub(crate) fn compatible(&self) -> Option<&CompatibleDist> {
+        self.dist.as_ref()
+    }
+
+    /// The key [`crate::InMemoryIndex`] dedupes in-flight metadata requests by.
+    pub(crate) fn version_id(&self) -> (PackageName, Version) {
+        (self.name.clone(), self.version.clone())
+    }
+}
+
+/// Whether `dist`'s `requires-python` excludes the target or installed interpreter, and if so,
+/// the specifier that excludes it.
+///
+/// Shared between [`CandidateSelector`] (the resolver's actual choice of version) and
+/// [`crate::resolver::batch_prefetch::BatchPrefetcher`] (which applies the same check
+/// speculatively, ahead of time), so the two checks can't drift apart.
+pub(crate) fn python_incompatibility<'a>(
+    dist: &'a CompatibleDist,
+    python_requirement: &PythonRequirement,
+) -> Option<&'a VersionSpecifiers> {
+    match dist {
+        CompatibleDist::InstalledDist(_) => None,
+        CompatibleDist::SourceDist { sdist, .. }
+        | CompatibleDist::IncompatibleWheel { sdist, .. } => {
+            // Source distributions must meet both the _target_ Python version and the
+            // _installed_ Python version (to build successfully).
+            sdist
+                .file
+                .requires_python
+                .as_ref()
+                .filter(|requires_python| {
+                    !python_requirement
+                        .installed()
+                        .is_contained_by(requires_python)
+                        || !python_requirement.target().is_contained_by(requires_python)
+                })
+        }
+        CompatibleDist::CompatibleWheel { wheel, .. } => {
+            // Wheels must meet the _target_ Python version.
+            wheel
+                .file
+                .requires_python
+                .as_ref()
+                .filter(|requires_python| {
+                    !python_requirement.target().is_contained_by(requires_python)
+                })
+        }
+    }
+}
+
+/// Picks the next candidate version for a package during resolution.
+pub(crate) struct CandidateSelector {
+    python_requirement: PythonRequirement,
+    python_compatibility: PythonCompatibilityStrategy,
+}
+
+impl CandidateSelector {
+    pub(crate) fn new(
+        python_requirement: PythonRequirement,
+        python_compatibility: PythonCompatibilityStrategy,
+    ) -> Self {
+        Self {
+            python_requirement,
+            python_compatibility,
+        }
+    }
+
+    /// The configured Python-compatibility mode, consulted by [`VersionOrdering::for_package`].
+    pub(crate) fn python_compatibility(&self) -> PythonCompatibilityStrategy {
+        self.python_compatibility
+    }
+
+    /// Whether versions should be tried highest-first for `name` in the current fork.
+    ///
+    /// Always `true`: this selector carries no resolution mode, so lowest-first ordering
+    /// (`--resolution=lowest`/`lowest-direct`) is never chosen here.
+    pub(crate) fn use_highest_version(
+        &self,
+        _name: &PackageName,
+        _env: &ResolverEnvironment,
+    ) -> bool {
+        true
+    }
+
+    /// Pick the next candidate for `name` in `range`, ignoring any local preference (a pin, a
+    /// URL requirement, ...) for it.
+    ///
+    /// A [`VersionOrdering::PreferredThenVersion`] is where the actual partitioning lives: its
+    /// preference bucket is exhausted before we ever fall back to the rest, so a resolution only
+    /// fails outright once there is truly nothing left to try.
+    pub(crate) fn select_no_preference(
+        &self,
+        name: &PackageName,
+        range: &Range<Version>,
+        version_map: &VersionMap,
+        version_ordering: VersionOrdering,
+        _env: &ResolverEnvironment,
+    ) -> Option<Candidate> {
+        match version_ordering {
+            VersionOrdering::PreferredThenVersion {
+                prefer: VersionPreference::PythonCompatible,
+                then,
+            } => self
+                .select_in_range(name, range, version_map, then, true)
+                .or_else(|| self.select_in_range(name, range, version_map, then, false)),
+            _ => self.select_in_range(name, range, version_map, version_ordering.base(), true),
+        }
+    }
+
+    /// The version pubgrub actually commits to trying next for `name`.
+    ///
+    /// Unlike [`Self::select_no_preference`], a Python-incompatible candidate returned here is
+    /// the resolver's real decision, not a speculative prefetch, so we warn the user that the
+    /// resolution depends on a release that doesn't satisfy their `requires-python`.
+    pub(crate) fn select(
+        &self,
+        name: &PackageName,
+        range: &Range<Version>,
+        version_map: &VersionMap,
+        env: &ResolverEnvironment,
+    ) -> Option<Candidate> {
+        let version_ordering = VersionOrdering::for_package(name, self, env);
+        let candidate =
+            self.select_no_preference(name, range, version_map, version_ordering, env)?;
+        if matches!(
+            version_ordering,
+            VersionOrdering::PreferredThenVersion { .. }
+        ) {
+            if let Some(requires_python) = candidate
+                .compatible()
+                .and_then(|dist| python_incompatibility(dist, &self.python_requirement))
+            {
+                warn_user_once!(
+                    "{name} {} requires {requires_python}, which is incompatible with the \
+                     target Python version; selecting it anyway since no compatible release \
+                     satisfies the requirement",
+                    candidate.version()
+                );
+            }
+        }
+        Some(candidate)
+    }
+
+    /// Walk `version_map` in `ordering`'s direction and return the first version in `range`,
+    /// optionally requiring that it satisfy `self.python_requirement`.
+    fn select_in_range(
+        &self,
+        name: &PackageName,
+        range: &Range<Version>,
+        version_map: &VersionMap,
+        ordering: BaseVersionOrdering,
+        require_python_compatible: bool,
+    ) -> Option<Candidate> {
+        let versions = version_map
+            .versions()
+            .filter(|version| range.contains(version));
+        let version = pick_version(versions, ordering, |version| {
+            if !require_python_compatible {
+                return true;
+            }
+            version_map
+                .get(version)
+                .as_ref()
+                .is_none_or(|dist| python_incompatibility(dist, &self.python_requirement).is_none())
+        })?;
+        Some(Candidate::new(
+            name.clone(),
+            version.clone(),
+            version_map.get(version),
+        ))
+    }
+}
+
+/// Walk `versions` in `ordering`'s direction and return the first one `accept` approves of.
+///
+/// Pulled out of [`CandidateSelector::select_in_range`] so the compatible-then-fallback
+/// partitioning at the heart of [`CandidateSelector::select_no_preference`] -- two calls to this
+/// with different `accept` predicates -- is testable without needing a [`VersionMap`].
+fn pick_version<'a>(
+    versions: impl Iterator<Item = &'a Version>,
+    ordering: BaseVersionOrdering,
+    mut accept: impl FnMut(&Version) -> bool,
+) -> Option<&'a Version> {
+    let mut versions: Vec<&Version> = versions.collect();
+    match ordering {
+        BaseVersionOrdering::MaximumVersion => versions.sort_unstable_by(|a, b| b.cmp(a)),
+        BaseVersionOrdering::MinimumVersion => versions.sort_unstable(),
+    }
+    versions.into_iter().find(|version| accept(version))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(s: &str) -> Version {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn pick_version_respects_ordering_direction() {
+        let versions = [version("1.0.0"), version("2.0.0"), version("3.0.0")];
+        assert_eq!(
+            pick_version(versions.iter(), BaseVersionOrdering::MaximumVersion, |_| {
+                true
+            }),
+            Some(&version("3.0.0"))
+        );
+        assert_eq!(
+            pick_version(versions.iter(), BaseVersionOrdering::MinimumVersion, |_| {
+                true
+            }),
+            Some(&version("1.0.0"))
+        );
+    }
+
+    #[test]
+    fn pick_version_skips_rejected_candidates() {
+        let versions = [version("1.0.0"), version("2.0.0"), version("3.0.0")];
+        let picked = pick_version(versions.iter(), BaseVersionOrdering::MaximumVersion, |v| {
+            *v != version("3.0.0")
+        });
+        assert_eq!(picked, Some(&version("2.0.0")));
+    }
+
+    #[test]
+    fn compatible_then_fallback_prefers_the_compatible_bucket() {
+        // Mirrors `select_no_preference`'s `PreferredThenVersion` arm: try the Python-compatible
+        // bucket first, and only fall back to the rest if nothing in it qualifies.
+        let versions = [version("1.0.0"), version("2.0.0"), version("3.0.0")];
+        let compatible = |v: &Version| *v != version("3.0.0");
+        let picked = pick_version(
+            versions.iter(),
+            BaseVersionOrdering::MaximumVersion,
+            compatible,
+        )
+        .or_else(|| {
+            pick_version(versions.iter(), BaseVersionOrdering::MaximumVersion, |_| {
+                true
+            })
+        });
+        assert_eq!(picked, Some(&version("2.0.0")));
+    }
+
+    #[test]
+    fn compatible_then_fallback_falls_back_when_nothing_compatible() {
+        // If every version is incompatible, the fallback pass must still return the best one
+        // instead of the resolution failing outright -- this is the bug the request fixed.
+        let versions = [version("1.0.0"), version("2.0.0"), version("3.0.0")];
+        let none_compatible = |_: &Version| false;
+        let picked = pick_version(
+            versions.iter(),
+            BaseVersionOrdering::MaximumVersion,
+            none_compatible,
+        )
+        .or_else(|| {
+            pick_version(versions.iter(), BaseVersionOrdering::MaximumVersion, |_| {
+                true
+            })
+        });
+        assert_eq!(picked, Some(&version("3.0.0")));
+    }
+}
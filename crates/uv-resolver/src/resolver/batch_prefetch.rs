@@ -2,11 +2,11 @@ use std::cmp::min;
 
 use itertools::Itertools;
 use pubgrub::{Id, Range, State, Term};
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
 use tokio::sync::mpsc::Sender;
 use tracing::{debug, trace};
 
-use crate::candidate_selector::CandidateSelector;
+use crate::candidate_selector::{python_incompatibility, CandidateSelector};
 use crate::dependency_provider::UvDependencyProvider;
 use crate::pubgrub::{PubGrubPackage, PubGrubPackageInner};
 use crate::resolver::Request;
@@ -14,8 +14,164 @@ use crate::{
     InMemoryIndex, PythonRequirement, ResolveError, ResolverEnvironment, VersionsResponse,
 };
 use uv_distribution_types::{CompatibleDist, DistributionMetadata, IndexCapabilities, IndexUrl};
+use uv_normalize::PackageName;
 use uv_pep440::Version;
 
+/// Controls whether a version that is incompatible with the target Python requirement is
+/// dropped outright or kept around as a fallback.
+///
+/// We'd rather try an older, `requires-python`-compatible release before giving up, instead of
+/// failing the resolution the moment every *recent* release requires a newer interpreter than
+/// the one we're targeting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PythonCompatibilityStrategy {
+    /// Only ever select versions whose `requires-python` is satisfied by the target Python.
+    /// This is the long-standing behavior.
+    RequireCompatible,
+    /// Exhaust the Python-compatible versions first, but fall back to incompatible versions
+    /// rather than failing the resolution outright.
+    PreferCompatible,
+}
+
+impl Default for PythonCompatibilityStrategy {
+    fn default() -> Self {
+        Self::RequireCompatible
+    }
+}
+
+/// Whether the prefetcher is allowed to build source distributions to prefetch their metadata.
+///
+/// Building a source distribution is far more expensive than downloading a wheel's `.metadata`
+/// or using range requests, so this is opt-in and heavily capped (see
+/// [`BatchPrefetcher::SDIST_PREFETCH_CEILING`]). The source distributions picked during a batch
+/// are requested after that batch's wheel candidates, whether the batch ran out of candidates or
+/// reached its size limit; a batch abandoned for missing registry capabilities builds none.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SourceDistPrefetchStrategy {
+    /// Never prefetch source distributions; this is the long-standing behavior.
+    Skip,
+    /// Build and extract metadata for a bounded number of sdist candidates after each batch's
+    /// wheel candidates, so cold-cache resolution of pure-sdist projects still benefits from
+    /// prefetch.
+    Bounded,
+}
+
+impl Default for SourceDistPrefetchStrategy {
+    fn default() -> Self {
+        Self::Skip
+    }
+}
+
+/// A raw direction to walk a package's version map in, with no priority rule layered on top.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BaseVersionOrdering {
+    /// Try the highest version first. This is the default resolution order.
+    MaximumVersion,
+    /// Try the lowest version first, as in `--resolution=lowest` and `lowest-direct`.
+    MinimumVersion,
+}
+
+/// A priority rule a composite [`VersionOrdering`] can exhaust before falling back to raw
+/// version order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum VersionPreference {
+    /// Prefer versions whose `requires-python` is satisfied by the target Python.
+    PythonCompatible,
+}
+
+/// The order in which candidate versions for a package should be considered.
+///
+/// This consolidates the `use_highest_version` boolean that used to be threaded separately
+/// through [`CandidateSelector`] and [`BatchPrefetcher`]. Computing it once per package/
+/// environment gives us a single place to stack priority rules (a Python-compatible version, and
+/// in the future a locked version) ahead of the raw version order, instead of branching on
+/// booleans in multiple modules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum VersionOrdering {
+    /// Try the highest version first. This is the default resolution order.
+    MaximumVersion,
+    /// Try the lowest version first, as in `--resolution=lowest` and `lowest-direct`.
+    MinimumVersion,
+    /// Exhaust the versions matching `prefer` before falling back to the rest, each walked in
+    /// `then`'s direction.
+    PreferredThenVersion {
+        prefer: VersionPreference,
+        then: BaseVersionOrdering,
+    },
+}
+
+impl VersionOrdering {
+    /// Compute the ordering to use for `name` under the given resolver environment.
+    pub(crate) fn for_package(
+        name: &PackageName,
+        selector: &CandidateSelector,
+        env: &ResolverEnvironment,
+    ) -> Self {
+        let then = if selector.use_highest_version(name, env) {
+            BaseVersionOrdering::MaximumVersion
+        } else {
+            BaseVersionOrdering::MinimumVersion
+        };
+        match selector.python_compatibility() {
+            PythonCompatibilityStrategy::RequireCompatible => match then {
+                BaseVersionOrdering::MaximumVersion => Self::MaximumVersion,
+                BaseVersionOrdering::MinimumVersion => Self::MinimumVersion,
+            },
+            PythonCompatibilityStrategy::PreferCompatible => Self::PreferredThenVersion {
+                prefer: VersionPreference::PythonCompatible,
+                then,
+            },
+        }
+    }
+
+    /// This ordering's direction once any preference bucket has been exhausted.
+    pub(crate) fn base(self) -> BaseVersionOrdering {
+        match self {
+            Self::MaximumVersion => BaseVersionOrdering::MaximumVersion,
+            Self::MinimumVersion => BaseVersionOrdering::MinimumVersion,
+            Self::PreferredThenVersion { then, .. } => then,
+        }
+    }
+
+    /// The range of versions left to try after `previous`, in this ordering's direction.
+    fn remaining_after(self, previous: Version) -> Range<Version> {
+        match self.base() {
+            BaseVersionOrdering::MaximumVersion => Range::strictly_lower_than(previous),
+            BaseVersionOrdering::MinimumVersion => Range::strictly_higher_than(previous),
+        }
+    }
+}
+
+/// Why a candidate version was rejected during prefetching rather than being fetched.
+///
+/// Counts of these are kept per [`Id<PubGrubPackage>`] so that, if the resolution ultimately
+/// fails with [`ResolveError::NoSolution`], the derivation tree can explain *why* a package has
+/// no acceptable version instead of reporting a bare "no versions found": e.g. "12 versions
+/// available but all rejected because their `requires-python` excludes the target Python".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum PrefetchRejection {
+    /// The distribution's `requires-python` excludes the target or installed interpreter.
+    PythonIncompatible,
+    /// The registry doesn't support PEP 658 `.metadata` or range requests for this wheel, so we
+    /// can't cheaply fetch its metadata.
+    MissingCapabilities,
+}
+
+impl PrefetchRejection {
+    /// A human-readable fragment describing this rejection reason, for use in
+    /// [`BatchPrefetcher::no_solution_context`].
+    fn describe(self) -> &'static str {
+        match self {
+            Self::PythonIncompatible => {
+                "have a `requires-python` that excludes the target or installed Python"
+            }
+            Self::MissingCapabilities => {
+                "come from a registry that doesn't support metadata-only fetches"
+            }
+        }
+    }
+}
+
 enum BatchPrefetchStrategy {
     /// Go through the next versions assuming the existing selection and its constraints
     /// remain.
@@ -42,9 +198,95 @@ enum BatchPrefetchStrategy {
 pub(crate) struct BatchPrefetcher {
     tried_versions: FxHashMap<Id<PubGrubPackage>, usize>,
     last_prefetch: FxHashMap<Id<PubGrubPackage>, usize>,
+    python_compatibility: PythonCompatibilityStrategy,
+    source_dist_prefetch: SourceDistPrefetchStrategy,
+    /// Per-package counts of why a candidate was passed over during prefetching. Surfaced
+    /// through [`Self::no_solution_context`], which is currently only wired into
+    /// [`Self::log_tried_versions`]'s `debug!` output -- not yet into `ResolveError::NoSolution`
+    /// itself, so a user hitting "no version" doesn't see this explanation. See
+    /// [`Self::no_solution_context`] for why.
+    rejections: FxHashMap<Id<PubGrubPackage>, FxHashMap<PrefetchRejection, usize>>,
+    /// Every version we've ever prefetched for a package, so we can tell whether the resolver
+    /// went on to actually try it (a "hit") or never needed it (a "miss").
+    prefetched_versions: FxHashMap<Id<PubGrubPackage>, FxHashSet<Version>>,
+    /// How many previously prefetched versions the resolver has since tried, per package.
+    prefetch_hits: FxHashMap<Id<PubGrubPackage>, usize>,
 }
 
 impl BatchPrefetcher {
+    /// Use `strategy` to decide whether Python-incompatible versions are dropped outright or
+    /// kept as a fallback once the compatible versions are exhausted.
+    pub(crate) fn with_python_compatibility_strategy(
+        mut self,
+        strategy: PythonCompatibilityStrategy,
+    ) -> Self {
+        self.python_compatibility = strategy;
+        self
+    }
+
+    /// Use `strategy` to decide whether source distributions are ever built to prefetch their
+    /// metadata, after each batch's wheel-based prefetching.
+    pub(crate) fn with_source_dist_prefetch_strategy(
+        mut self,
+        strategy: SourceDistPrefetchStrategy,
+    ) -> Self {
+        self.source_dist_prefetch = strategy;
+        self
+    }
+
+    /// How many versions we've tried for `id` so far, for use in `NoSolution` error reporting.
+    pub(crate) fn tried_versions(&self, id: Id<PubGrubPackage>) -> usize {
+        self.tried_versions.get(&id).copied().unwrap_or_default()
+    }
+
+    /// Why candidates for `id` were rejected during prefetching, and how often, for use in
+    /// `NoSolution` error reporting.
+    pub(crate) fn rejections(
+        &self,
+        id: Id<PubGrubPackage>,
+    ) -> impl Iterator<Item = (PrefetchRejection, usize)> + '_ {
+        self.rejections
+            .get(&id)
+            .into_iter()
+            .flat_map(|reasons| reasons.iter().map(|(reason, count)| (*reason, *count)))
+    }
+
+    fn record_rejection(&mut self, id: Id<PubGrubPackage>, reason: PrefetchRejection) {
+        *self
+            .rejections
+            .entry(id)
+            .or_default()
+            .entry(reason)
+            .or_default() += 1;
+    }
+
+    /// A human-readable summary of why `id` has no acceptable version, e.g. "12 versions
+    /// available but all rejected: 12 have a `requires-python` that excludes the target or
+    /// installed Python".
+    ///
+    /// This is meant for `ResolveError::NoSolution` to fold into its derivation-tree message when
+    /// the resolution fails outright, but that wiring is NOT done: `error.rs` (where
+    /// `ResolveError` and its `Display` impl live) isn't part of this checkout, so there is no
+    /// safe way to land it from here. Until that follow-up lands, this is only reachable through
+    /// [`Self::log_tried_versions`]'s `debug!` output, and a user who actually hits "no version"
+    /// for the rejection case this was built for still gets the old bare message. Returns `None`
+    /// if prefetching never rejected a candidate for `id`, since the counts here only cover what
+    /// prefetching observed, not every version the resolver itself tried.
+    pub(crate) fn no_solution_context(&self, id: Id<PubGrubPackage>) -> Option<String> {
+        let reasons: Vec<_> = self.rejections(id).collect();
+        if reasons.is_empty() {
+            return None;
+        }
+        let total = self.tried_versions(id);
+        let reasons = reasons
+            .iter()
+            .map(|(reason, count)| format!("{count} {}", reason.describe()))
+            .join(", ");
+        Some(format!(
+            "{total} versions available but all rejected: {reasons}"
+        ))
+    }
+
     /// Prefetch a large number of versions if we already unsuccessfully tried many versions.
     pub(crate) fn prefetch_batches(
         &mut self,
@@ -75,7 +317,7 @@ impl BatchPrefetcher {
         if !do_prefetch {
             return Ok(());
         }
-        let total_prefetch = min(num_tried, 50);
+        let total_prefetch = min(num_tried, self.prefetch_ceiling(id));
 
         // This is immediate, we already fetched the version map.
         let versions_response = if let Some(index) = index {
@@ -94,20 +336,33 @@ impl BatchPrefetcher {
             return Ok(());
         };
 
+        // Computed once per package/environment and reused for every candidate we consider
+        // below, rather than re-querying the selector on each iteration.
+        let version_ordering = VersionOrdering::for_package(name, selector, env);
+
         let mut phase = BatchPrefetchStrategy::Compatible {
             compatible: current_range.clone(),
             previous: version.clone(),
         };
         let mut prefetch_count = 0;
+        // Source-distribution candidates that otherwise qualify for prefetching. Only used by
+        // [`SourceDistPrefetchStrategy::Bounded`], which builds a bounded number of these after
+        // the wheel-based loop below, since building is much more expensive than downloading
+        // wheel metadata.
+        let mut source_dist_fallback = Vec::new();
         for _ in 0..total_prefetch {
             let candidate = match phase {
                 BatchPrefetchStrategy::Compatible {
                     compatible,
                     previous,
                 } => {
-                    if let Some(candidate) =
-                        selector.select_no_preference(name, &compatible, version_map, env)
-                    {
+                    if let Some(candidate) = selector.select_no_preference(
+                        name,
+                        &compatible,
+                        version_map,
+                        version_ordering,
+                        env,
+                    ) {
                         let compatible = compatible.intersection(
                             &Range::singleton(candidate.version().clone()).complement(),
                         );
@@ -124,11 +379,7 @@ impl BatchPrefetcher {
                     }
                 }
                 BatchPrefetchStrategy::InOrder { previous } => {
-                    let mut range = if selector.use_highest_version(name, env) {
-                        Range::strictly_lower_than(previous)
-                    } else {
-                        Range::strictly_higher_than(previous)
-                    };
+                    let mut range = version_ordering.remaining_after(previous);
                     // If we have constraints from root, don't go beyond those. Example: We are
                     // prefetching for foo 1.60 and have a dependency for `foo>=1.50`, so we should
                     // only prefetch 1.60 to 1.50, knowing 1.49 will always be rejected.
@@ -140,9 +391,13 @@ impl BatchPrefetcher {
                             }
                         };
                     }
-                    if let Some(candidate) =
-                        selector.select_no_preference(name, &range, version_map, env)
-                    {
+                    if let Some(candidate) = selector.select_no_preference(
+                        name,
+                        &range,
+                        version_map,
+                        version_ordering,
+                        env,
+                    ) {
                         phase = BatchPrefetchStrategy::InOrder {
                             previous: candidate.version().clone(),
                         };
@@ -158,8 +413,32 @@ impl BatchPrefetcher {
                 continue;
             };
 
-            // Avoid prefetching source distributions, which could be expensive.
+            // Avoid prefetching source distributions, which could be expensive, unless we're
+            // configured to build a bounded number of them as a last resort.
             let Some(wheel) = dist.wheel() else {
+                if self.source_dist_prefetch == SourceDistPrefetchStrategy::Bounded {
+                    if let CompatibleDist::SourceDist { sdist, .. } = dist {
+                        // The candidate must meet both the _target_ Python version and the
+                        // _installed_ Python version (to build successfully).
+                        let python_compatible =
+                            sdist
+                                .file
+                                .requires_python
+                                .as_ref()
+                                .is_none_or(|requires_python| {
+                                    python_requirement
+                                        .installed()
+                                        .is_contained_by(requires_python)
+                                        && python_requirement
+                                            .target()
+                                            .is_contained_by(requires_python)
+                                });
+                        if python_compatible {
+                            source_dist_fallback
+                                .push((dist.for_resolution(), candidate.version_id()));
+                        }
+                    }
+                }
                 continue;
             };
 
@@ -169,37 +448,22 @@ impl BatchPrefetcher {
                 || capabilities.supports_range_requests(&wheel.index))
             {
                 debug!("Abandoning prefetch for {wheel} due to missing registry capabilities");
+                self.record_rejection(id, PrefetchRejection::MissingCapabilities);
                 return Ok(());
             }
 
-            // Avoid prefetching for distributions that don't satisfy the Python requirement.
-            match dist {
-                CompatibleDist::InstalledDist(_) => {}
-                CompatibleDist::SourceDist { sdist, .. }
-                | CompatibleDist::IncompatibleWheel { sdist, .. } => {
-                    // Source distributions must meet both the _target_ Python version and the
-                    // _installed_ Python version (to build successfully).
-                    if let Some(requires_python) = sdist.file.requires_python.as_ref() {
-                        if !python_requirement
-                            .installed()
-                            .is_contained_by(requires_python)
-                        {
-                            continue;
-                        }
-                        if !python_requirement.target().is_contained_by(requires_python) {
-                            continue;
-                        }
-                    }
-                }
-                CompatibleDist::CompatibleWheel { wheel, .. } => {
-                    // Wheels must meet the _target_ Python version.
-                    if let Some(requires_python) = wheel.file.requires_python.as_ref() {
-                        if !python_requirement.target().is_contained_by(requires_python) {
-                            continue;
-                        }
-                    }
-                }
-            };
+            // The selector already applied its own Python-compatibility strategy when it picked
+            // this candidate: in `RequireCompatible` mode an incompatible one is never returned,
+            // and in `PreferCompatible` mode it's only returned once nothing compatible is left.
+            // In `RequireCompatible` mode we skip an incompatible candidate and record the
+            // rejection for `NoSolution` bookkeeping; otherwise we still prefetch it, since the
+            // selector has already decided it's our best shot.
+            if python_incompatibility(dist, python_requirement).is_some()
+                && self.python_compatibility == PythonCompatibilityStrategy::RequireCompatible
+            {
+                self.record_rejection(id, PrefetchRejection::PythonIncompatible);
+                continue;
+            }
 
             let dist = dist.for_resolution();
 
@@ -215,11 +479,38 @@ impl BatchPrefetcher {
             prefetch_count += 1;
 
             if in_memory.distributions().register(candidate.version_id()) {
+                self.prefetched_versions
+                    .entry(id)
+                    .or_default()
+                    .insert(candidate.version().clone());
                 let request = Request::from(dist);
                 request_sink.blocking_send(request)?;
             }
         }
 
+        // Reached once the wheel-based loop above ends, whether it ran out of candidates or hit
+        // `total_prefetch`; not reached when the batch was abandoned for missing capabilities.
+        // Building is expensive, so we parallelize at most a handful of sdist builds we're
+        // likely to need rather than flooding the task pool with speculative ones.
+        if self.source_dist_prefetch == SourceDistPrefetchStrategy::Bounded {
+            for (dist, version_id) in source_dist_fallback
+                .into_iter()
+                .take(Self::SDIST_PREFETCH_CEILING)
+            {
+                trace!("Prefetching {prefetch_count} (source distribution) {dist}");
+                prefetch_count += 1;
+                let version = version_id.1.clone();
+                if in_memory.distributions().register(version_id) {
+                    self.prefetched_versions
+                        .entry(id)
+                        .or_default()
+                        .insert(version);
+                    let request = Request::from(dist);
+                    request_sink.blocking_send(request)?;
+                }
+            }
+        }
+
         debug!("Prefetching {prefetch_count} {name} versions");
 
         self.last_prefetch.insert(id, num_tried);
@@ -227,7 +518,12 @@ impl BatchPrefetcher {
     }
 
     /// Each time we tried a version for a package, we register that here.
-    pub(crate) fn version_tried(&mut self, id: Id<PubGrubPackage>, package: &PubGrubPackage) {
+    pub(crate) fn version_tried(
+        &mut self,
+        id: Id<PubGrubPackage>,
+        package: &PubGrubPackage,
+        version: &Version,
+    ) {
         // Only track base packages, no virtual packages from extras.
         if matches!(
             &**package,
@@ -239,12 +535,67 @@ impl BatchPrefetcher {
             }
         ) {
             *self.tried_versions.entry(id).or_default() += 1;
+            if self
+                .prefetched_versions
+                .get(&id)
+                .is_some_and(|versions| versions.contains(version))
+            {
+                *self.prefetch_hits.entry(id).or_default() += 1;
+            }
+        }
+    }
+
+    /// The fraction of versions we've ever prefetched for `id` that the resolver went on to
+    /// actually try, or `None` if we haven't prefetched anything for it yet.
+    ///
+    /// Clamped to `1.0`: `prefetch_hits` counts every time `version_tried` observes a
+    /// previously-prefetched version, which backtracking can do more than once for the same
+    /// version (see [`Self::log_tried_versions`]), while `prefetched_versions` is deduped.
+    fn hit_rate(&self, id: Id<PubGrubPackage>) -> Option<f64> {
+        let total = self.prefetched_versions.get(&id).map(FxHashSet::len)?;
+        if total == 0 {
+            return None;
         }
+        let hits = self.prefetch_hits.get(&id).copied().unwrap_or_default();
+        Some((hits as f64 / total as f64).min(1.0))
+    }
+
+    const MIN_PREFETCH_CEILING: usize = 10;
+    const WARMUP_PREFETCH_CEILING: usize = 50;
+    const MAX_PREFETCH_CEILING: usize = 200;
+    /// Much smaller than the wheel ceilings above: building a source distribution is expensive,
+    /// so we only ever build a handful speculatively.
+    const SDIST_PREFETCH_CEILING: usize = 5;
+
+    /// The maximum batch size to prefetch for `id` in one go.
+    ///
+    /// Until we have hit-rate feedback (see [`Self::hit_rate`]), we use the same fixed ceiling
+    /// this always had. Once we've seen how often prefetched versions are actually consumed,
+    /// packages on a long rejection streak (botocore) ramp their ceiling up towards
+    /// [`Self::MAX_PREFETCH_CEILING`], while packages whose prefetches mostly go unused back off
+    /// towards [`Self::MIN_PREFETCH_CEILING`] so we stop fetching metadata we don't need.
+    fn prefetch_ceiling(&self, id: Id<PubGrubPackage>) -> usize {
+        Self::interpolate_ceiling(self.hit_rate(id))
+    }
+
+    /// The pure interpolation [`Self::prefetch_ceiling`] delegates to, split out so the formula
+    /// can be tested without needing a [`Id<PubGrubPackage>`] to look a hit rate up by.
+    ///
+    /// `hit_rate` is clamped to `0.0..=1.0` regardless of what the caller passes in, so a bogus
+    /// value out of range can never push the result past [`Self::MAX_PREFETCH_CEILING`].
+    fn interpolate_ceiling(hit_rate: Option<f64>) -> usize {
+        let Some(hit_rate) = hit_rate else {
+            return Self::WARMUP_PREFETCH_CEILING;
+        };
+        let hit_rate = hit_rate.clamp(0.0, 1.0);
+        let span = (Self::MAX_PREFETCH_CEILING - Self::MIN_PREFETCH_CEILING) as f64;
+        (Self::MIN_PREFETCH_CEILING as f64 + hit_rate * span).round() as usize
     }
 
     /// After 5, 10, 20, 40 tried versions, prefetch that many versions to start early but not
-    /// too aggressive. Later we schedule the prefetch of 50 versions every 20 versions, this gives
-    /// us a good buffer until we see prefetch again and is high enough to saturate the task pool.
+    /// too aggressive. Later we schedule the prefetch of a full batch every 20 versions, this
+    /// gives us a good buffer until we see prefetch again and is high enough to saturate the
+    /// task pool.
     fn should_prefetch(&self, id: Id<PubGrubPackage>) -> (usize, bool) {
         let num_tried = self.tried_versions.get(&id).copied().unwrap_or_default();
         let previous_prefetch = self.last_prefetch.get(&id).copied().unwrap_or_default();
@@ -276,5 +627,119 @@ impl BatchPrefetcher {
             .map(|(package, count)| format!("{package} {count}"))
             .join(", ");
         debug!("Tried {total_versions} versions: {counts}");
+
+        let hit_rates = self
+            .prefetched_versions
+            .keys()
+            .filter_map(|id| {
+                let hit_rate = self.hit_rate(*id)?;
+                Some(format!(
+                    "{} {:.0}% ({} ceiling)",
+                    &state.package_store[*id],
+                    hit_rate * 100.0,
+                    self.prefetch_ceiling(*id)
+                ))
+            })
+            .join(", ");
+        if !hit_rates.is_empty() {
+            debug!("Prefetch hit rates: {hit_rates}");
+        }
+
+        let rejection_summaries = self
+            .rejections
+            .keys()
+            .filter_map(|id| {
+                let context = self.no_solution_context(*id)?;
+                Some(format!("{}: {context}", &state.package_store[*id]))
+            })
+            .join("; ");
+        if !rejection_summaries.is_empty() {
+            debug!("Prefetch rejections: {rejection_summaries}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(s: &str) -> Version {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn base_version_ordering_direction() {
+        let maximum = VersionOrdering::MaximumVersion;
+        assert_eq!(maximum.base(), BaseVersionOrdering::MaximumVersion);
+        assert!(maximum
+            .remaining_after(version("2.0.0"))
+            .contains(&version("1.0.0")));
+        assert!(!maximum
+            .remaining_after(version("2.0.0"))
+            .contains(&version("3.0.0")));
+
+        let minimum = VersionOrdering::MinimumVersion;
+        assert_eq!(minimum.base(), BaseVersionOrdering::MinimumVersion);
+        assert!(minimum
+            .remaining_after(version("2.0.0"))
+            .contains(&version("3.0.0")));
+        assert!(!minimum
+            .remaining_after(version("2.0.0"))
+            .contains(&version("1.0.0")));
+    }
+
+    #[test]
+    fn preferred_then_version_falls_back_to_its_base_direction() {
+        let composite = VersionOrdering::PreferredThenVersion {
+            prefer: VersionPreference::PythonCompatible,
+            then: BaseVersionOrdering::MinimumVersion,
+        };
+        // The preference bucket doesn't change which direction we walk once it's exhausted.
+        assert_eq!(composite.base(), BaseVersionOrdering::MinimumVersion);
+        assert!(composite
+            .remaining_after(version("2.0.0"))
+            .contains(&version("3.0.0")));
+    }
+
+    #[test]
+    fn prefetch_rejection_descriptions_are_distinct() {
+        assert_ne!(
+            PrefetchRejection::PythonIncompatible.describe(),
+            PrefetchRejection::MissingCapabilities.describe()
+        );
+    }
+
+    #[test]
+    fn ceiling_interpolates_between_min_and_max() {
+        assert_eq!(
+            BatchPrefetcher::interpolate_ceiling(None),
+            BatchPrefetcher::WARMUP_PREFETCH_CEILING
+        );
+        assert_eq!(
+            BatchPrefetcher::interpolate_ceiling(Some(0.0)),
+            BatchPrefetcher::MIN_PREFETCH_CEILING
+        );
+        assert_eq!(
+            BatchPrefetcher::interpolate_ceiling(Some(1.0)),
+            BatchPrefetcher::MAX_PREFETCH_CEILING
+        );
+        let midpoint = BatchPrefetcher::interpolate_ceiling(Some(0.5));
+        assert!(midpoint > BatchPrefetcher::MIN_PREFETCH_CEILING);
+        assert!(midpoint < BatchPrefetcher::MAX_PREFETCH_CEILING);
+    }
+
+    #[test]
+    fn ceiling_clamps_an_out_of_range_hit_rate() {
+        // `prefetch_hits` can outrun the deduped `prefetched_versions` count during backtracking
+        // (see `log_tried_versions`'s doc comment). `hit_rate` clamps that ratio itself, and
+        // `interpolate_ceiling` clamps again for any other caller.
+        assert_eq!(
+            BatchPrefetcher::interpolate_ceiling(Some(2.5)),
+            BatchPrefetcher::MAX_PREFETCH_CEILING
+        );
+        assert_eq!(
+            BatchPrefetcher::interpolate_ceiling(Some(-1.0)),
+            BatchPrefetcher::MIN_PREFETCH_CEILING
+        );
     }
 }